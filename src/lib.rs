@@ -2,60 +2,387 @@
 //! It allows readers to read from the cell without ever blocking while the writer might block when writing.
 //! This is achived by storing to copies of the data one for the readers and one for the writer.
 #![deny(missing_docs)]
-use std::ops::Deref;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use left_right::Absorb;
 
-struct SetOp<T>(T);
+/// An operation queued against the cell. `Update` carries a closure rather than a value so
+/// that read-modify-write edits can be computed from the writer's up-to-date copy.
+enum Op<T> {
+    /// Replace the current value outright.
+    Set(T),
+    /// Replace the current value with the result of a closure applied to it.
+    Update(UpdateOp<T>),
+    /// Stamp the generation that will become visible to readers at the next publish. Queued by
+    /// `WriteHandle::publish` itself so the generation flips atomically with the data it
+    /// describes instead of racing it through a side channel.
+    Bump(u64),
+}
+
+type UpdateFn<T> = Box<dyn FnOnce(&T) -> T + Send>;
 
-impl<T> Absorb<SetOp<T>> for Inner<T>
+/// The closure is only ever invoked once, against the writer's up-to-date copy in
+/// `absorb_first`. The result is cached here so `absorb_second` can apply that exact value
+/// to the lagging copy instead of re-running the closure against stale data.
+struct UpdateOp<T> {
+    f: Option<UpdateFn<T>>,
+    result: Option<T>,
+}
+
+impl<T> Absorb<Op<T>> for Inner<T>
 where
     T: Clone,
 {
-    fn absorb_first(&mut self, operation: &mut SetOp<T>, _: &Self) {
-        self.0 = operation.0.clone();
+    fn absorb_first(&mut self, operation: &mut Op<T>, _: &Self) {
+        match operation {
+            Op::Set(value) => self.value = value.clone(),
+            Op::Update(update) => {
+                let f = update.f.take().expect("update op absorbed twice");
+                let next = f(&self.value);
+                update.result = Some(next.clone());
+                self.value = next;
+            }
+            Op::Bump(generation) => self.generation = *generation,
+        }
     }
 
-    fn absorb_second(&mut self, operation: SetOp<T>, _: &Self) {
-        self.0 = operation.0;
+    fn absorb_second(&mut self, operation: Op<T>, _: &Self) {
+        match operation {
+            Op::Set(value) => self.value = value,
+            Op::Update(update) => {
+                self.value = update.result.expect("update op not absorbed first");
+            }
+            Op::Bump(generation) => self.generation = generation,
+        }
     }
 
     fn drop_first(self: Box<Self>) {}
 
     fn sync_with(&mut self, first: &Self) {
-        self.0 = first.0.clone()
+        self.value = first.value.clone();
+        self.generation = first.generation;
     }
 }
 
+/// The value half flips atomically with its generation on every publish, since both live in the
+/// same struct swapped by `left_right` — that's what lets guards report a generation that's
+/// guaranteed to match the data they're viewing.
 #[derive(Clone)]
-struct Inner<T>(T);
+struct Inner<T> {
+    value: T,
+    generation: u64,
+}
+
+#[cfg(feature = "async")]
+mod notify {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        },
+        task::{Context, Poll, Waker},
+    };
+
+    /// A minimal slab of waiting tasks: each [`Changed`] owns one slot for its whole lifetime,
+    /// so repeated polls of the same future replace its waker instead of piling up duplicates.
+    #[derive(Default)]
+    struct Slab {
+        entries: Vec<Option<Waker>>,
+        free: Vec<usize>,
+    }
+
+    impl Slab {
+        fn insert(&mut self, waker: Waker) -> usize {
+            if let Some(index) = self.free.pop() {
+                self.entries[index] = Some(waker);
+                index
+            } else {
+                self.entries.push(Some(waker));
+                self.entries.len() - 1
+            }
+        }
+
+        fn update(&mut self, index: usize, waker: Waker) {
+            self.entries[index] = Some(waker);
+        }
+
+        fn remove(&mut self, index: usize) {
+            self.entries[index] = None;
+            self.free.push(index);
+        }
+
+        /// Wakes every stored waker. The slots stay reserved for their owners until `remove`.
+        fn wake_all(&mut self) {
+            for slot in self.entries.iter_mut() {
+                if let Some(waker) = slot.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Wakes tasks waiting on [`super::ReadHandle::changed`] once the shared generation counter
+    /// advances.
+    #[derive(Default)]
+    pub(crate) struct Notify {
+        wakers: Mutex<Slab>,
+    }
+
+    impl Notify {
+        pub(crate) fn wake_all(&self) {
+            self.wakers.lock().unwrap().wake_all();
+        }
+
+        pub(crate) fn changed<'a>(&'a self, generation: &'a AtomicU64, last_seen: u64) -> Changed<'a> {
+            Changed {
+                notify: self,
+                generation,
+                last_seen,
+                slot: None,
+            }
+        }
+    }
+
+    /// The future returned by [`super::ReadHandle::changed`]. Holds at most one slot in the
+    /// notify list for its whole lifetime, releasing it on drop if cancelled before resolving.
+    pub(crate) struct Changed<'a> {
+        notify: &'a Notify,
+        generation: &'a AtomicU64,
+        last_seen: u64,
+        slot: Option<usize>,
+    }
+
+    impl Changed<'_> {
+        /// Releases this future's slab slot, if it holds one. Called whenever `poll` resolves to
+        /// `Ready` as well as on `Drop`, so a slot is never left "owned" by a future that has
+        /// stopped waiting on it — otherwise `Slab::insert` could later hand that slot to a
+        /// different `Changed`, which a late `Drop` of this one would then evict.
+        fn release_slot(&mut self) {
+            if let Some(index) = self.slot.take() {
+                self.notify.wakers.lock().unwrap().remove(index);
+            }
+        }
+    }
+
+    impl Future for Changed<'_> {
+        type Output = u64;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+            let this = self.get_mut();
+            let generation = this.generation.load(Ordering::Acquire);
+            if generation != this.last_seen {
+                this.release_slot();
+                return Poll::Ready(generation);
+            }
+
+            let mut wakers = this.notify.wakers.lock().unwrap();
+            match this.slot {
+                Some(index) => wakers.update(index, cx.waker().clone()),
+                None => this.slot = Some(wakers.insert(cx.waker().clone())),
+            }
+            drop(wakers);
+
+            // `publish` may have run between the check above and registering the waker.
+            let generation = this.generation.load(Ordering::Acquire);
+            if generation != this.last_seen {
+                this.release_slot();
+                Poll::Ready(generation)
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Drop for Changed<'_> {
+        fn drop(&mut self) {
+            self.release_slot();
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+use notify::Notify;
+
+#[cfg(feature = "async")]
+type NotifyHandle = Arc<Notify>;
+#[cfg(not(feature = "async"))]
+type NotifyHandle = ();
+
+/// Creates the pair of [`NotifyHandle`]s shared between a [`WriteHandle`] and its [`ReadHandle`].
+#[cfg(feature = "async")]
+fn new_notify_pair() -> (NotifyHandle, NotifyHandle) {
+    let notify = Arc::new(Notify::default());
+    (notify.clone(), notify)
+}
+#[cfg(not(feature = "async"))]
+fn new_notify_pair() -> (NotifyHandle, NotifyHandle) {
+    ((), ())
+}
+
+#[cfg(feature = "async")]
+fn notify_publish(notify: &NotifyHandle) {
+    notify.wake_all();
+}
+#[cfg(not(feature = "async"))]
+fn notify_publish(_notify: &NotifyHandle) {}
+
+#[cfg(feature = "async")]
+fn clone_notify(notify: &NotifyHandle) -> NotifyHandle {
+    notify.clone()
+}
+#[cfg(not(feature = "async"))]
+fn clone_notify(_notify: &NotifyHandle) -> NotifyHandle {}
 
 /// A handle to the read half of the cell. Getting a value from the read handle will never block.
-pub struct ReadHandle<T>(left_right::ReadHandle<Inner<T>>);
+pub struct ReadHandle<T>(
+    left_right::ReadHandle<Inner<T>>,
+    NotifyHandle,
+    Arc<AtomicU64>,
+);
 impl<T> ReadHandle<T> {
     /// Gets the value from the cell. Returns [`None`] if the [`WriteHandle`] as been dropped.
-    pub fn get(&self) -> Option<ReadGuard<T>> {
-        self.0.enter().map(|guard| ReadGuard(guard))
+    pub fn get(&self) -> Option<ReadGuard<'_, T>> {
+        // The generation lives inside `Inner` itself, so it flips atomically with the value on
+        // every publish. Reading it out of the guard we just entered, rather than from a side
+        // channel, is what guarantees it always matches the data the guard is viewing.
+        self.0.enter().map(|guard| {
+            let generation = guard.as_ref().generation;
+            ReadGuard(guard, generation)
+        })
     }
 
     /// # Safety
     /// The user of this function must be sure that the [`WriteHandle`] has not been dropped.
-    pub unsafe fn get_unchecked(&self) -> ReadGuard<T> {
-        self.0
-            .enter()
-            .map(|guard| ReadGuard(guard))
-            .unwrap_unchecked()
+    pub unsafe fn get_unchecked(&self) -> ReadGuard<'_, T> {
+        let guard = self.0.enter().unwrap_unchecked();
+        let generation = guard.as_ref().generation;
+        ReadGuard(guard, generation)
+    }
+
+    /// Clones the current value out of the cell and immediately releases the guard. Unlike
+    /// [`ReadHandle::get`], the returned value does not hold back [`WriteHandle::publish`], so
+    /// it's a better fit for readers that keep the value around for a while.
+    pub fn get_cloned(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.get().map(|guard| (*guard).clone())
+    }
+
+    /// Returns the generation of the value most recently published. Every
+    /// [`WriteHandle::publish`] increments this.
+    pub fn generation(&self) -> u64 {
+        self.2.load(Ordering::Acquire)
+    }
+
+    /// Like [`ReadHandle::get`], but returns [`None`] instead of a guard if nothing has been
+    /// published since `since`. Useful for "reload only on change" cache-invalidation patterns.
+    pub fn get_if_newer(&self, since: u64) -> Option<ReadGuard<'_, T>> {
+        if self.generation() <= since {
+            return None;
+        }
+        self.get()
+    }
+
+    /// Waits for the next [`WriteHandle::publish`] after `last_seen`, resolving to the new
+    /// generation. Resolves immediately if a publish has already happened since `last_seen`.
+    #[cfg(feature = "async")]
+    pub fn changed(&self, last_seen: u64) -> impl Future<Output = u64> + '_ {
+        self.1.changed(&self.2, last_seen)
     }
 }
 
 /// A reference guard to the read half of the cell. [`WriteHandle::publish`] will block until this is dropped.
-pub struct ReadGuard<'a, T>(left_right::ReadGuard<'a, Inner<T>>);
+pub struct ReadGuard<'a, T>(left_right::ReadGuard<'a, Inner<T>>, u64);
 
 impl<T> Deref for ReadGuard<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        &self.0.as_ref().0
+        &self.0.as_ref().value
+    }
+}
+
+impl<'a, T> ReadGuard<'a, T> {
+    /// Returns the generation of the value this guard is viewing, captured when the guard was
+    /// created. Compare against [`ReadHandle::generation`] to detect staleness.
+    pub fn generation(&self) -> u64 {
+        self.1
+    }
+
+    /// Projects the guard onto part of `T`, keeping the underlying guard alive so
+    /// [`WriteHandle::publish`] still blocks until the returned [`MappedReadGuard`] is dropped.
+    pub fn map<U, F>(self, f: F) -> MappedReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let value = f(&self.0.as_ref().value) as *const U;
+        MappedReadGuard {
+            _guard: Box::new(self.0),
+            value,
+            generation: self.1,
+        }
+    }
+
+    /// Like [`ReadGuard::map`], but the projection may fail, returning the original guard back on failure.
+    pub fn try_map<U, F>(self, f: F) -> Result<MappedReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(&self.0.as_ref().value) {
+            Some(value) => {
+                let value = value as *const U;
+                let generation = self.1;
+                Ok(MappedReadGuard {
+                    _guard: Box::new(self.0),
+                    value,
+                    generation,
+                })
+            }
+            None => Err(self),
+        }
+    }
+}
+
+/// Implemented for any read-side guard so a [`MappedReadGuard`] can keep one alive without
+/// having to carry the original `T` as a type parameter.
+trait AnyReadGuard<'a> {}
+
+impl<'a, T> AnyReadGuard<'a> for left_right::ReadGuard<'a, Inner<T>> {}
+
+/// A read guard holding a projection into `T`, produced by [`ReadGuard::map`] or [`ReadGuard::try_map`].
+/// [`WriteHandle::publish`] will block until this is dropped.
+pub struct MappedReadGuard<'a, U> {
+    _guard: Box<dyn AnyReadGuard<'a> + 'a>,
+    value: *const U,
+    generation: u64,
+}
+
+impl<U> Deref for MappedReadGuard<'_, U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: `value` points into the data kept alive by `_guard` for the lifetime of `self`.
+        unsafe { &*self.value }
+    }
+}
+
+impl<U> MappedReadGuard<'_, U> {
+    /// Returns the generation of the value this guard is viewing, carried over from the
+    /// [`ReadGuard`] it was projected from. Compare against [`ReadHandle::generation`] to detect
+    /// staleness.
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
 }
 
@@ -63,14 +390,22 @@ impl<T> Clone for ReadHandle<T>
 where
     T: Clone,
 {
+    // `NotifyHandle` is `()` without the `async` feature, which trips `clippy::let_unit_value`
+    // here even though the binding is needed to make this generic over both feature states.
+    #[allow(clippy::let_unit_value)]
     fn clone(&self) -> Self {
-        ReadHandle(self.0.clone())
+        let notify = clone_notify(&self.1);
+        ReadHandle(self.0.clone(), notify, self.2.clone())
     }
 }
 
 /// A handle to the write half of the cell.
 /// When this handle is dropped the backing data is also dropped.
-pub struct WriteHandle<T: Clone>(left_right::WriteHandle<Inner<T>, SetOp<T>>);
+pub struct WriteHandle<T: Clone>(
+    left_right::WriteHandle<Inner<T>, Op<T>>,
+    NotifyHandle,
+    Arc<AtomicU64>,
+);
 
 impl<T> WriteHandle<T>
 where
@@ -78,23 +413,70 @@ where
 {
     /// Set the value of the cell.
     pub fn set(&mut self, value: T) {
-        self.0.append(SetOp(value));
+        self.0.append(Op::Set(value));
+    }
+
+    /// Set the value of the cell to the result of applying `f` to the current value, without
+    /// having to track the current value outside of the cell.
+    pub fn update<F>(&mut self, f: F)
+    where
+        F: FnOnce(&T) -> T + Send + 'static,
+    {
+        self.0.append(Op::Update(UpdateOp {
+            f: Some(Box::new(f)),
+            result: None,
+        }));
+    }
+
+    /// Like [`WriteHandle::update`], but `f` mutates a clone of the current value in place
+    /// instead of returning the next one.
+    pub fn update_in_place<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut T) + Send + 'static,
+    {
+        self.update(move |current| {
+            let mut next = current.clone();
+            f(&mut next);
+            next
+        });
     }
 
     /// Make the changes the to cell since the last set visible to the readers.
     pub fn publish(&mut self) {
+        // Queue the new generation as an op of its own so it rides along with the rest of the
+        // pending ops through `absorb_first`/`absorb_second` and flips into view atomically with
+        // them, rather than being set independently after the fact. The shared counter is only
+        // advanced once the flip is done, so readers never see a generation before its value.
+        let generation = self.2.load(Ordering::Acquire) + 1;
+        self.0.append(Op::Bump(generation));
         self.0.publish();
+        self.2.store(generation, Ordering::Release);
+        notify_publish(&self.1);
     }
 }
 
 /// Creates a new left-right-cell and returns the read and write handle.
 pub fn new<T: Clone>(value: T) -> (WriteHandle<T>, ReadHandle<T>) {
-    let (w, r) = left_right::new_from_empty::<Inner<T>, SetOp<T>>(Inner(value));
-    (WriteHandle(w), ReadHandle(r))
+    let (w, r) = left_right::new_from_empty::<Inner<T>, Op<T>>(Inner {
+        value,
+        generation: 0,
+    });
+    let (write_notify, read_notify) = new_notify_pair();
+    let generation = Arc::new(AtomicU64::new(0));
+    (
+        WriteHandle(w, write_notify, generation.clone()),
+        ReadHandle(r, read_notify, generation),
+    )
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn write_handle_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<super::WriteHandle<i32>>();
+    }
+
     #[test]
     fn it_works() {
         let (mut w, r) = super::new(false);
@@ -111,4 +493,169 @@ mod tests {
         t.join().unwrap();
         assert!(true);
     }
+
+    #[test]
+    fn update_reads_the_current_value() {
+        let (mut w, r) = super::new(1);
+
+        w.update(|value| value + 1);
+        w.publish();
+        assert_eq!(*r.get().unwrap(), 2);
+
+        w.update_in_place(|value| *value *= 10);
+        w.publish();
+        assert_eq!(*r.get().unwrap(), 20);
+    }
+
+    #[test]
+    fn get_cloned_releases_the_guard() {
+        let (mut w, r) = super::new(vec![1, 2, 3]);
+
+        w.set(vec![4, 5, 6]);
+        w.publish();
+
+        assert_eq!(r.get_cloned(), Some(vec![4, 5, 6]));
+    }
+
+    #[test]
+    fn get_if_newer_detects_staleness() {
+        let (mut w, r) = super::new(1);
+        let seen = r.generation();
+
+        assert!(r.get_if_newer(seen).is_none());
+
+        w.set(2);
+        w.publish();
+
+        let guard = r.get_if_newer(seen).unwrap();
+        assert_eq!(*guard, 2);
+        assert_eq!(guard.generation(), r.generation());
+    }
+
+    #[test]
+    fn mapped_guard_keeps_its_generation() {
+        let (mut w, r) = super::new(vec![1, 2, 3]);
+
+        w.set(vec![4, 5, 6]);
+        w.publish();
+
+        let guard = r.get().unwrap();
+        let generation = guard.generation();
+        let mapped = guard.map(|v| &v[0]);
+
+        assert_eq!(*mapped, 4);
+        assert_eq!(mapped.generation(), generation);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn changed_resolves_after_publish() {
+        use std::{
+            future::Future,
+            sync::Arc,
+            task::{Context, Poll, Wake},
+        };
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let (mut w, r) = super::new(1);
+        let waker = std::task::Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut fut = Box::pin(r.changed(r.generation()));
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+
+        w.set(2);
+        w.publish();
+
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Ready(r.generation()));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn changed_reuses_its_slot_across_polls() {
+        use std::{
+            future::Future,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            task::{Context, Poll, Wake},
+        };
+
+        struct CountingWaker(AtomicUsize);
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let (mut w, r) = super::new(1);
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = std::task::Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut fut = Box::pin(r.changed(r.generation()));
+        // Poll twice while still pending, as a runtime would on spurious wakeups. This must
+        // replace the same slot rather than registering a second waker for the same future.
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+
+        w.set(2);
+        w.publish();
+
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn changed_does_not_evict_a_slot_it_no_longer_owns() {
+        use std::{
+            future::Future,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            task::{Context, Poll, Wake},
+        };
+
+        struct CountingWaker(AtomicUsize);
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let (mut w, r) = super::new(1);
+        let a_waker = std::task::Waker::from(Arc::new(CountingWaker(AtomicUsize::new(0))));
+        let mut a_cx = Context::from_waker(&a_waker);
+
+        let mut a = Box::pin(r.changed(r.generation()));
+        assert_eq!(a.as_mut().poll(&mut a_cx), Poll::Pending);
+
+        w.set(2);
+        w.publish();
+
+        // `a` resolves to `Ready` here but is deliberately not dropped yet. Its slot must be
+        // released as part of resolving, not left behind for a later `Drop` to clean up.
+        assert_eq!(a.as_mut().poll(&mut a_cx), Poll::Ready(r.generation()));
+
+        let c_counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let c_waker = std::task::Waker::from(c_counter.clone());
+        let mut c_cx = Context::from_waker(&c_waker);
+
+        let mut c = Box::pin(r.changed(r.generation()));
+        assert_eq!(c.as_mut().poll(&mut c_cx), Poll::Pending);
+
+        // Dropping `a` after `c` has registered must not evict `c`'s waker.
+        drop(a);
+
+        w.set(3);
+        w.publish();
+
+        assert_eq!(c_counter.0.load(Ordering::SeqCst), 1);
+    }
 }